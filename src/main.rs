@@ -1,55 +1,220 @@
-use rand::{prelude::SliceRandom, thread_rng};
+use clap::Parser;
+use rand::{prelude::SliceRandom, thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::HashSet;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-const NUM_TRIES: usize = 1000000;
-const NUM_BOXES: usize = 100;
-const NUM_PICKS: usize = 50;
-
 type Boxes = Vec<usize>;
 
+/// Monte Carlo simulation of the "100 prisoners" problem.
+#[derive(Parser)]
+#[command(about = "Simulate the 100 prisoners problem with selectable strategies")]
+struct Args {
+    /// Number of boxes (and prisoners) per trial.
+    #[arg(long, default_value_t = 100)]
+    boxes: usize,
+
+    /// Number of boxes each prisoner may open.
+    #[arg(long, default_value_t = 50)]
+    picks: usize,
+
+    /// Number of independent trials to run.
+    #[arg(long, default_value_t = 1_000_000)]
+    tries: usize,
+
+    /// Strategy to run, or `all` to run every registered strategy.
+    #[arg(long, default_value = "all")]
+    strategy: String,
+
+    /// Master seed for reproducible runs. Omit to seed from OS entropy.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Analyse the cycle structure of the generated permutations instead of
+    /// running the strategies.
+    #[arg(long)]
+    analyze: bool,
+
+    /// z-score for the Wilson confidence interval (1.96 ≈ 95%).
+    #[arg(long, default_value_t = 1.96)]
+    confidence: f64,
+
+    /// Animate a single prisoner's search with termion instead of running the
+    /// full simulation.
+    #[arg(long)]
+    visualize: bool,
+
+    /// Prisoner to follow in `--visualize` mode.
+    #[arg(long, default_value_t = 0)]
+    prisoner: usize,
+}
+
+/// Parameters shared by every part of a simulation run.
+#[derive(Clone, Copy)]
+struct Config {
+    boxes: usize,
+    picks: usize,
+    tries: usize,
+    seed: u64,
+    z: f64,
+}
+
 fn main() {
-    let result = run_sim::<RandomStrategy>();
-    println!(
-        "Random strategy: {}% success",
-        100.0 * result as f32 / NUM_TRIES as f32
-    );
+    let args = Args::parse();
+    let config = Config {
+        boxes: args.boxes,
+        picks: args.picks,
+        tries: args.tries,
+        seed: args.seed.unwrap_or_else(|| thread_rng().gen()),
+        z: args.confidence,
+    };
+
+    if args.visualize {
+        // `all` isn't a single strategy to watch; fall back to `loop`, whose
+        // cycle-following is the whole point of the animation.
+        let name = if args.strategy == "all" {
+            "loop"
+        } else {
+            args.strategy.as_str()
+        };
+        match STRATEGIES.iter().find(|e| e.name == name) {
+            Some(entry) => (entry.visualize)(&config, args.prisoner),
+            None => {
+                eprintln!("Unknown strategy `{}`", args.strategy);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.analyze {
+        run_analysis(&config);
+        return;
+    }
 
-    let result = run_sim::<LoopStrategy>();
+    if args.strategy == "all" {
+        for entry in STRATEGIES {
+            run_registered(entry, &config);
+        }
+    } else {
+        match STRATEGIES.iter().find(|e| e.name == args.strategy) {
+            Some(entry) => run_registered(entry, &config),
+            None => {
+                let names: Vec<&str> = STRATEGIES.iter().map(|e| e.name).collect();
+                eprintln!(
+                    "Unknown strategy `{}`; available: {}, all",
+                    args.strategy,
+                    names.join(", ")
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn run_registered(entry: &StrategyEntry, config: &Config) {
+    let result = (entry.run)(config);
+    let (lo, hi) = result.wilson_interval(config.z);
     println!(
-        "Loop strategy:   {}% success",
-        100.0 * result as f32 / NUM_TRIES as f32
+        "{:<16} {:.4}% success (Wilson CI, z={}: [{:.4}%, {:.4}%])",
+        format!("{} strategy:", entry.label),
+        100.0 * result.estimate(),
+        config.z,
+        100.0 * lo,
+        100.0 * hi,
     );
 }
 
+/// A strategy exposed on the command line. `run` invokes `run_sim` for the
+/// concrete `Strategy` implementation the entry stands for.
+struct StrategyEntry {
+    name: &'static str,
+    label: &'static str,
+    run: fn(&Config) -> SimResult,
+    visualize: fn(&Config, usize),
+}
+
+/// Outcome of a simulation: how many of `trials` trials succeeded.
+struct SimResult {
+    successes: usize,
+    trials: usize,
+}
+
+impl SimResult {
+    /// Point estimate of the success proportion.
+    fn estimate(&self) -> f64 {
+        self.successes as f64 / self.trials as f64
+    }
+
+    /// Wilson score confidence interval `(lower, upper)` for the success
+    /// proportion at the given z-score.
+    fn wilson_interval(&self, z: f64) -> (f64, f64) {
+        let n = self.trials as f64;
+        let p = self.estimate();
+        let z2 = z * z;
+        let denom = 1.0 + z2 / n;
+        let center = (p + z2 / (2.0 * n)) / denom;
+        let half = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denom;
+        (center - half, center + half)
+    }
+}
+
+/// Registry of every strategy selectable with `--strategy`.
+const STRATEGIES: &[StrategyEntry] = &[
+    StrategyEntry {
+        name: "random",
+        label: "Random",
+        run: run_sim::<RandomStrategy>,
+        visualize: visualize_run::<RandomStrategy>,
+    },
+    StrategyEntry {
+        name: "loop",
+        label: "Loop",
+        run: run_sim::<LoopStrategy>,
+        visualize: visualize_run::<LoopStrategy>,
+    },
+];
+
 // Simulate all prisoners multiple times to get a percentage success rate
-fn run_sim<S: Strategy>() -> usize {
+fn run_sim<S: Strategy>(config: &Config) -> SimResult {
     let success = AtomicUsize::new(0);
 
-    // Runs each group of prisoners in parallel
-    (0..NUM_TRIES).into_par_iter().for_each(|_| {
-        if run_all::<S>() {
+    // Runs each group of prisoners in parallel. Each trial derives its own
+    // RNG purely from the master seed and the trial index, so the aggregate
+    // result is identical regardless of how rayon schedules the work.
+    (0..config.tries).into_par_iter().for_each(|trial| {
+        let mut rng = trial_rng(config.seed, trial);
+        if run_all::<S, _>(config, &mut rng) {
             success.fetch_add(1, Ordering::Relaxed);
         }
     });
 
-    success.load(Ordering::Relaxed)
+    SimResult {
+        successes: success.load(Ordering::Relaxed),
+        trials: config.tries,
+    }
 }
 
 // All prisoners searching for themselves. Returns `true` if all found.
-fn run_all<S: Strategy>() -> bool {
-    let boxes = make_boxes();
+fn run_all<S: Strategy, R: Rng>(config: &Config, rng: &mut R) -> bool {
+    let boxes = make_boxes(config.boxes, rng);
 
     // Loop over prisoners
-    (0..NUM_BOXES).all(|index| run_single::<S>(index, &boxes))
+    (0..config.boxes).all(|index| run_single::<S, R>(index, &boxes, config, rng))
 }
 
 /// A single prisoner searching for themselves. Returns `true` if found.
-fn run_single<S: Strategy>(index: usize, boxes: &[usize]) -> bool {
-    let mut strategy = S::new(index);
+fn run_single<S: Strategy, R: Rng>(
+    index: usize,
+    boxes: &[usize],
+    config: &Config,
+    rng: &mut R,
+) -> bool {
+    let mut strategy = S::new(index, config.boxes, config.picks, rng);
     let mut last_inside = None;
 
-    for _ in 0..NUM_PICKS {
+    for _ in 0..config.picks {
         // Get index of box
         let next_index = strategy.next_index(last_inside);
         let found = boxes[next_index];
@@ -62,35 +227,286 @@ fn run_single<S: Strategy>(index: usize, boxes: &[usize]) -> bool {
     false
 }
 
-fn make_boxes() -> Boxes {
-    let mut rng = thread_rng();
-
-    let mut boxes: Boxes = (0..NUM_BOXES).collect();
-    boxes.shuffle(&mut rng);
+fn make_boxes<R: Rng>(num_boxes: usize, rng: &mut R) -> Boxes {
+    let mut boxes: Boxes = (0..num_boxes).collect();
+    boxes.shuffle(rng);
     boxes
 }
 
+/// `LoopStrategy` succeeds for a trial exactly when the permutation's longest
+/// cycle is no longer than `picks`. This mode makes that visible: it builds a
+/// histogram of longest-cycle lengths over every trial and compares the
+/// empirical success fraction against the closed-form probability.
+fn run_analysis(config: &Config) {
+    let n = config.boxes;
+
+    // One histogram bucket per possible longest-cycle length (1..=n).
+    let hist = (0..config.tries)
+        .into_par_iter()
+        .fold(
+            || vec![0usize; n + 1],
+            |mut acc, trial| {
+                let mut rng = trial_rng(config.seed, trial);
+                let boxes = make_boxes(n, &mut rng);
+                acc[longest_cycle(&boxes)] += 1;
+                acc
+            },
+        )
+        .reduce(
+            || vec![0usize; n + 1],
+            |mut a, b| {
+                for (slot, count) in a.iter_mut().zip(b) {
+                    *slot += count;
+                }
+                a
+            },
+        );
+
+    println!("Longest-cycle histogram over {} trials:", config.tries);
+    for (len, count) in hist.iter().enumerate().skip(1) {
+        if *count > 0 {
+            println!("  {len:>4}: {count}");
+        }
+    }
+
+    let within: usize = hist.iter().take(config.picks + 1).sum();
+    let empirical = within as f64 / config.tries as f64;
+    println!("Empirical P(longest cycle <= {}): {empirical}", config.picks);
+
+    if let Some(exact) = closed_form_success(n, config.picks) {
+        println!("Closed-form probability:            {exact}");
+    } else {
+        println!(
+            "Closed-form probability requires picks >= boxes / 2 ({} >= {})",
+            config.picks,
+            n / 2
+        );
+    }
+}
+
+/// Length of the longest cycle in the permutation `boxes`, found by walking
+/// `i -> boxes[i] -> ...` from each unvisited index until it returns to the
+/// start, marking every index reached along the way.
+fn longest_cycle(boxes: &[usize]) -> usize {
+    let mut visited = vec![false; boxes.len()];
+    let mut longest = 0;
+
+    for start in 0..boxes.len() {
+        if visited[start] {
+            continue;
+        }
+
+        let mut len = 0;
+        let mut i = start;
+        while !visited[i] {
+            visited[i] = true;
+            i = boxes[i];
+            len += 1;
+        }
+
+        longest = longest.max(len);
+    }
+
+    longest
+}
+
+/// Exact success probability of `LoopStrategy`: for `picks = m >= n / 2` it is
+/// `1 - Σ_{k=m+1}^{n} 1/k`, since a random permutation of `n` has a cycle of
+/// length exactly `k > n/2` with probability `1/k` and at most one such long
+/// cycle can exist. Returns `None` when the formula does not apply.
+fn closed_form_success(n: usize, m: usize) -> Option<f64> {
+    if m < n / 2 {
+        return None;
+    }
+
+    let tail: f64 = (m + 1..=n).map(|k| 1.0 / k as f64).sum();
+    Some(1.0 - tail)
+}
+
+/// Number of boxes drawn per row in the visualization grid.
+const VIZ_COLS: usize = 10;
+
+/// Animate one prisoner's search over a single generated permutation, driving
+/// the `Strategy` trait exactly as `run_single` does. Opened boxes reveal the
+/// number inside; the box turns green when the prisoner finds their own number
+/// and red if the pick budget runs out first.
+fn visualize_run<S: Strategy>(config: &Config, prisoner: usize) {
+    use std::io::Write;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use termion::{clear, color, cursor};
+
+    let prisoner = prisoner.min(config.boxes.saturating_sub(1));
+    let mut rng = trial_rng(config.seed, 0);
+    let boxes = make_boxes(config.boxes, &mut rng);
+    let mut strategy = S::new(prisoner, config.boxes, config.picks, &mut rng);
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+
+    let reset = format!("{}", color::Fg(color::Reset));
+    let rows = config.boxes.div_ceil(VIZ_COLS);
+    let status_y = (rows * 2 + 4) as u16;
+
+    write!(out, "{}{}", clear::All, cursor::Hide).unwrap();
+    write!(
+        out,
+        "{}Prisoner {prisoner} searching — {} of {} boxes allowed",
+        cursor::Goto(1, 1),
+        config.picks,
+        config.boxes,
+    )
+    .unwrap();
+
+    // Draw every box closed to start with.
+    for i in 0..config.boxes {
+        draw_box(&mut out, i, None, &reset);
+    }
+    out.flush().unwrap();
+    sleep(Duration::from_millis(400));
+
+    let mut last_inside = None;
+    let mut last_opened = None;
+    let mut found = false;
+
+    for _ in 0..config.picks {
+        let next_index = strategy.next_index(last_inside);
+        let inside = boxes[next_index];
+        last_opened = Some(next_index);
+
+        // Highlight the box currently being opened.
+        draw_box(
+            &mut out,
+            next_index,
+            Some(inside),
+            &format!("{}", color::Fg(color::Yellow)),
+        );
+        out.flush().unwrap();
+        sleep(Duration::from_millis(400));
+
+        if inside == prisoner {
+            draw_box(
+                &mut out,
+                next_index,
+                Some(inside),
+                &format!("{}", color::Fg(color::Green)),
+            );
+            found = true;
+            break;
+        }
+
+        // Leave opened-but-wrong boxes tinted so the chain stays visible.
+        draw_box(
+            &mut out,
+            next_index,
+            Some(inside),
+            &format!("{}", color::Fg(color::Cyan)),
+        );
+        last_inside = Some(inside);
+    }
+
+    if !found {
+        // Mark the last box the prisoner reached as a failure.
+        if let Some(last) = last_opened {
+            draw_box(
+                &mut out,
+                last,
+                Some(boxes[last]),
+                &format!("{}", color::Fg(color::Red)),
+            );
+        }
+    }
+
+    let verdict = if found {
+        format!("{}Found own number!{}", color::Fg(color::Green), reset)
+    } else {
+        format!("{}Pick budget exhausted.{}", color::Fg(color::Red), reset)
+    };
+    write!(out, "{}{}{}", cursor::Goto(1, status_y), verdict, cursor::Show).unwrap();
+    writeln!(out).unwrap();
+    out.flush().unwrap();
+}
+
+/// Render a single box of the visualization grid at its grid position, showing
+/// the number inside once `inside` is known and tinting the cell with `fg`.
+fn draw_box<W: std::io::Write>(out: &mut W, i: usize, inside: Option<usize>, fg: &str) {
+    use termion::{color, cursor};
+
+    let col = (i % VIZ_COLS) as u16;
+    let row = (i / VIZ_COLS) as u16;
+    let x = col * 8 + 1;
+    let y = row * 2 + 3;
+
+    let inside = match inside {
+        Some(value) => format!("{value:>2}"),
+        None => "..".to_string(),
+    };
+
+    write!(
+        out,
+        "{}{}[{i:>2}:{inside}]{}",
+        cursor::Goto(x, y),
+        fg,
+        color::Fg(color::Reset),
+    )
+    .unwrap();
+}
+
+/// Build the deterministic RNG for a single trial. The trial index is run
+/// through a SplitMix64 avalanche before being mixed into the master seed so
+/// that adjacent indices yield uncorrelated streams.
+fn trial_rng(master_seed: u64, trial_index: usize) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(master_seed ^ mix(trial_index as u64))
+}
+
+/// SplitMix64 finalizing avalanche of a 64-bit value.
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 trait Strategy {
-    fn new(index: usize) -> Self;
+    fn new<R: Rng>(index: usize, num_boxes: usize, num_picks: usize, rng: &mut R) -> Self;
     fn next_index(&mut self, last_inside: Option<usize>) -> usize;
 }
 
-/// Randomly tests boxes. To do this we just create our own "boxes"
-/// (shuffled indices) and pop off the back of the list once per turn
-/// to get a new index - and we're guaranteed not to have any repeats.
+/// Randomly tests boxes. A prisoner only ever opens `num_picks` boxes, so
+/// rather than shuffling all `num_boxes` indices up front we draw the distinct
+/// indices lazily with Floyd's algorithm: one call to `next_index` advances a
+/// single step of the loop `for j in num_boxes - num_picks .. num_boxes`,
+/// picking `t` in `0..=j` and inserting `t`, or `j` when `t` was already taken.
+/// This yields distinct indices in O(picks) space and does no sampling work
+/// for picks the prisoner never reaches after an early find.
 struct RandomStrategy {
-    try_queue: Boxes,
+    rng: ChaCha8Rng,
+    j: usize,
+    chosen: HashSet<usize>,
 }
 
 impl Strategy for RandomStrategy {
-    fn new(_: usize) -> Self {
+    fn new<R: Rng>(_: usize, num_boxes: usize, num_picks: usize, rng: &mut R) -> Self {
         Self {
-            try_queue: make_boxes(),
+            // Seed an owned RNG from the trial RNG so the stream stays
+            // deterministic without holding a borrow across the prisoner loop.
+            rng: ChaCha8Rng::seed_from_u64(rng.gen()),
+            j: num_boxes.saturating_sub(num_picks),
+            chosen: HashSet::with_capacity(num_picks),
         }
     }
 
     fn next_index(&mut self, _: Option<usize>) -> usize {
-        self.try_queue.pop().unwrap()
+        let j = self.j;
+        self.j += 1;
+
+        let t = self.rng.gen_range(0..=j);
+        if self.chosen.insert(t) {
+            t
+        } else {
+            self.chosen.insert(j);
+            j
+        }
     }
 }
 
@@ -102,7 +518,7 @@ struct LoopStrategy {
 }
 
 impl Strategy for LoopStrategy {
-    fn new(index: usize) -> Self {
+    fn new<R: Rng>(index: usize, _: usize, _: usize, _: &mut R) -> Self {
         Self { index }
     }
 
@@ -113,3 +529,44 @@ impl Strategy for LoopStrategy {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_form_matches_known_value() {
+        // The classic 100/50 case: success probability ≈ 0.31183.
+        let p = closed_form_success(100, 50).unwrap();
+        assert!((p - 0.311_827).abs() < 1e-5, "got {p}");
+    }
+
+    #[test]
+    fn closed_form_requires_half() {
+        assert!(closed_form_success(100, 49).is_none());
+        assert!(closed_form_success(100, 50).is_some());
+    }
+
+    #[test]
+    fn trial_rng_is_reproducible() {
+        // Same seed and index always produce the same permutation.
+        let a = make_boxes(100, &mut trial_rng(42, 7));
+        let b = make_boxes(100, &mut trial_rng(42, 7));
+        assert_eq!(a, b);
+
+        // Adjacent indices produce different permutations.
+        let c = make_boxes(100, &mut trial_rng(42, 8));
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn wilson_interval_brackets_estimate() {
+        let result = SimResult {
+            successes: 31,
+            trials: 100,
+        };
+        let (lo, hi) = result.wilson_interval(1.96);
+        assert!(lo < result.estimate() && result.estimate() < hi);
+        assert!(lo >= 0.0 && hi <= 1.0);
+    }
+}